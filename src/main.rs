@@ -1,21 +1,121 @@
 use bytes::Bytes;
 use chrono::naive::NaiveDate;
+use clap::Parser;
+use log::{debug, error, info, warn};
+use megalodon::generator;
+use megalodon::megalodon::PostStatusInputOptions;
+use megalodon::SNS;
 use percent_encoding::percent_decode_str;
-use reqwest::blocking::{Client, ClientBuilder};
-use reqwest::{header, Url};
-use serde::{de, Deserialize, Deserializer};
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
+use reqwest::{header, StatusCode, Url};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::env::var;
 use std::fmt::Display;
-use std::fs::{read, write};
+use std::fs::{hard_link, read, write};
 use std::io::ErrorKind;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::str::FromStr;
+use std::thread::sleep;
 use std::time::Duration;
+use tokio::runtime::Runtime;
+use viuer::Config as ViuerConfig;
+
+/// Number of attempts for a retryable request, including the first one.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Base delay for exponential backoff between retries.
+const BASE_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Upper bound for the backoff delay, regardless of attempt count.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn backoff_delay(attempt: u32) -> Duration {
+    BASE_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_RETRY_DELAY)
+}
+
+fn retry_after_delay(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Sends a request built fresh by `build_request` on every attempt, making up to
+/// `MAX_ATTEMPTS` attempts with exponential backoff on timeouts and retryable status codes
+/// (honoring a `Retry-After` header given in seconds). Non-retryable errors are returned
+/// immediately; if all attempts are exhausted, the last error is returned.
+fn send_with_retry(build_request: impl Fn() -> RequestBuilder) -> Result<Response, String> {
+    let mut attempt = 0;
+    loop {
+        match build_request().send() {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) => {
+                let status = response.status();
+                if attempt + 1 >= MAX_ATTEMPTS || !is_retryable_status(status) {
+                    return Err(format!("Request failed with status {status}"));
+                }
+                let delay = retry_after_delay(&response).unwrap_or_else(|| backoff_delay(attempt));
+                warn!("Request failed with status {status}, retrying in {delay:?}");
+                sleep(delay);
+            }
+            Err(e) => {
+                if attempt + 1 >= MAX_ATTEMPTS || !e.is_timeout() {
+                    return Err(format!("Error sending request: {e}"));
+                }
+                let delay = backoff_delay(attempt);
+                warn!("Error sending request: {e}, retrying in {delay:?}");
+                sleep(delay);
+            }
+        }
+        attempt += 1;
+    }
+}
+
+/// Download the NASA Astronomy Picture of the Day, or backfill a range of past days.
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Cli {
+    /// Fetch the APOD for a specific date instead of today (YYYY-MM-DD).
+    #[arg(long, conflicts_with_all = ["start_date", "end_date"])]
+    date: Option<NaiveDate>,
+
+    /// Start of a date range to backfill (requires --end-date).
+    #[arg(long, requires = "end_date")]
+    start_date: Option<NaiveDate>,
+
+    /// End of a date range to backfill (requires --start-date).
+    #[arg(long, requires = "start_date")]
+    end_date: Option<NaiveDate>,
+
+    /// Render the downloaded image inline in the terminal before printing its path.
+    #[arg(long)]
+    preview: bool,
+}
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Deserialize)]
 struct Config {
     api_key: Option<String>,
     image_dir: Option<PathBuf>,
+    mastodon_instance: Option<String>,
+    mastodon_token: Option<String>,
 }
 
 fn load_config() -> Result<Config, String> {
@@ -34,7 +134,11 @@ fn load_config() -> Result<Config, String> {
             }
         }
     };
-    serde_json::from_slice(&file_content).map_err(|e| format!("Unable to parse config: {e}"))
+    serde_json::from_slice(&file_content).map_err(|e| {
+        let e = format!("Unable to parse config: {e}");
+        error!("{e}");
+        e
+    })
 }
 
 fn from_str<'de, T, D>(deserializer: D) -> Result<T, D::Error>
@@ -73,44 +177,158 @@ struct ApodData {
 
 const USER_AGENT: &str = "I CAN HAZ STARS?";
 
-fn fetch_current_data(client: &Client, api_key: &str) -> Result<ApodData, String> {
-    client
-        .get("https://api.nasa.gov/planetary/apod")
-        .header(header::ACCEPT, "application/json")
-        .query(&[("api_key", api_key)])
-        .send()
-        .map_err(|e| format!("Error fetching metadata: {e}"))?
-        .json::<ApodData>()
-        .map_err(|e| format!("Error parsing metadata: {e}"))
+fn fetch_current_data(
+    client: &Client,
+    api_key: &str,
+    date: Option<NaiveDate>,
+) -> Result<ApodData, String> {
+    debug!("Requesting APOD metadata for date={date:?}");
+    send_with_retry(|| {
+        let mut request = client
+            .get("https://api.nasa.gov/planetary/apod")
+            .header(header::ACCEPT, "application/json")
+            .query(&[("api_key", api_key)]);
+        if let Some(date) = date {
+            request = request.query(&[("date", date.to_string())]);
+        }
+        request
+    })
+    .map_err(|e| format!("Error fetching metadata: {e}"))?
+    .json::<ApodData>()
+    .map_err(|e| {
+        let e = format!("Error parsing metadata: {e}");
+        error!("{e}");
+        e
+    })
 }
 
-fn get_image_url(image_data: &ApodData) -> Result<&Url, String> {
-    match &image_data.media {
-        MediaType::Image { hdurl: url } => Ok(url),
-        MediaType::Video {} => Err("Unable to fetch image, media type is video".to_owned()),
+fn fetch_data_range(
+    client: &Client,
+    api_key: &str,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<Vec<ApodData>, String> {
+    debug!("Requesting APOD metadata for start_date={start_date}, end_date={end_date}");
+    send_with_retry(|| {
+        client
+            .get("https://api.nasa.gov/planetary/apod")
+            .header(header::ACCEPT, "application/json")
+            .query(&[("api_key", api_key)])
+            .query(&[
+                ("start_date", start_date.to_string()),
+                ("end_date", end_date.to_string()),
+            ])
+    })
+    .map_err(|e| format!("Error fetching metadata: {e}"))?
+    .json::<Vec<ApodData>>()
+    .map_err(|e| {
+        let e = format!("Error parsing metadata: {e}");
+        error!("{e}");
+        e
+    })
+}
+
+fn get_image_url(client: &Client, apod_data: &ApodData) -> Result<Url, String> {
+    match &apod_data.media {
+        MediaType::Image { hdurl } => {
+            debug!("Using HD url {hdurl} for {}", apod_data.date);
+            Ok(hdurl.clone())
+        }
+        MediaType::Video {} => {
+            let url = resolve_video_thumbnail(client, apod_data)?;
+            debug!("Resolved video thumbnail url {url} for {}", apod_data.date);
+            Ok(url)
+        }
     }
 }
 
-fn fetch_hd_image(client: &Client, url: &Url) -> Result<Bytes, String> {
-    client
-        .get(url.clone())
+/// Extracts a YouTube video ID from an embed (`/embed/<id>`), watch (`?v=<id>`) or
+/// short (`youtu.be/<id>`) URL.
+fn youtube_video_id(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+
+    if host == "youtu.be" {
+        return url.path_segments()?.next_back().map(str::to_owned);
+    }
+
+    if host.ends_with("youtube.com") {
+        let path_segments: Vec<&str> = url.path_segments()?.collect();
+        if let [.., "embed", id] | [.., "v", id] = path_segments.as_slice() {
+            return Some((*id).to_owned());
+        }
+        return url
+            .query_pairs()
+            .find(|(key, _)| key == "v")
+            .map(|(_, value)| value.into_owned());
+    }
+
+    None
+}
+
+fn youtube_thumbnail_url(client: &Client, video_id: &str) -> Result<Url, String> {
+    let maxres = Url::parse(&format!(
+        "https://img.youtube.com/vi/{video_id}/maxresdefault.jpg"
+    ))
+    .map_err(|e| format!("Invalid thumbnail URL: {e}"))?;
+
+    let maxres_exists = client
+        .head(maxres.clone())
         .send()
-        .map_err(|e| format!("Error fetching image: {e}"))?
-        .bytes()
-        .map_err(|e| format!("Unable to read image: {e}"))
+        .map(|response| response.status().is_success())
+        .unwrap_or(false);
+
+    if maxres_exists {
+        return Ok(maxres);
+    }
+
+    Url::parse(&format!(
+        "https://img.youtube.com/vi/{video_id}/hqdefault.jpg"
+    ))
+    .map_err(|e| format!("Invalid thumbnail URL: {e}"))
 }
 
-fn write_image(
-    mut dir: PathBuf,
-    apod_data: &ApodData,
-    url: &Url,
-    image: &[u8],
-) -> Result<PathBuf, String> {
-    dir.push(image_filename(apod_data, url));
+fn apod_page_url(date: NaiveDate) -> Url {
+    let page = format!("ap{}.html", date.format("%y%m%d"));
+    Url::parse("https://apod.nasa.gov/apod/")
+        .and_then(|base| base.join(&page))
+        .expect("APOD page URL is always valid")
+}
+
+/// APOD pages are plain HTML with the preview image as the first `<IMG SRC="...">` tag.
+fn extract_preview_image_url(html: &str, page_url: &Url) -> Option<Url> {
+    let start = html.to_ascii_lowercase().find("<img src=\"")? + "<img src=\"".len();
+    let end = html[start..].find('"')?;
+    page_url.join(&html[start..start + end]).ok()
+}
+
+fn fetch_apod_preview_image_url(client: &Client, date: NaiveDate) -> Result<Url, String> {
+    let page_url = apod_page_url(date);
+    let html = send_with_retry(|| client.get(page_url.clone()))
+        .map_err(|e| format!("Error reading APOD page: {e}"))?
+        .text()
+        .map_err(|e| format!("Error reading APOD page: {e}"))?;
 
-    write(&dir, image).map_err(|e| format!("Unable to write image data: {e})"))?;
+    extract_preview_image_url(&html, &page_url)
+        .ok_or_else(|| "Unable to find a preview image on the APOD page".to_owned())
+}
+
+/// Resolves a still image for a video entry: a YouTube thumbnail if the embed URL is
+/// recognized, or the preview image from the APOD page itself otherwise.
+fn resolve_video_thumbnail(client: &Client, apod_data: &ApodData) -> Result<Url, String> {
+    if let Some(video_id) = youtube_video_id(&apod_data.url) {
+        return youtube_thumbnail_url(client, &video_id);
+    }
 
-    Ok(dir)
+    fetch_apod_preview_image_url(client, apod_data.date)
+}
+
+fn fetch_hd_image(client: &Client, url: &Url) -> Result<Bytes, String> {
+    let image = send_with_retry(|| client.get(url.clone()))
+        .map_err(|e| format!("Error fetching image: {e}"))?
+        .bytes()
+        .map_err(|e| format!("Error fetching image: {e}"))?;
+    info!("Downloaded {} bytes from {url}", image.len());
+    Ok(image)
 }
 
 fn image_filename(apod_data: &ApodData, url: &Url) -> String {
@@ -121,7 +339,206 @@ fn image_filename(apod_data: &ApodData, url: &Url) -> String {
         .unwrap_or_else(|| format!("{}", apod_data.date))
 }
 
-fn main() -> Result<(), String> {
+const MANIFEST_FILENAME: &str = ".apod-manifest.json";
+
+/// Maps the SHA-256 (hex-encoded) of previously downloaded image bytes to the filename
+/// they were stored under, so images NASA re-uploads under a different date can be
+/// hardlinked instead of stored again.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Deserialize, Serialize)]
+struct Manifest {
+    #[serde(flatten)]
+    known_hashes: HashMap<String, PathBuf>,
+}
+
+fn load_manifest(image_dir: &Path) -> Manifest {
+    match read(image_dir.join(MANIFEST_FILENAME)) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+        Err(_) => Default::default(),
+    }
+}
+
+fn save_manifest(image_dir: &Path, manifest: &Manifest) -> Result<(), String> {
+    let content = serde_json::to_vec_pretty(manifest)
+        .map_err(|e| format!("Unable to serialize manifest: {e}"))?;
+    write(image_dir.join(MANIFEST_FILENAME), content)
+        .map_err(|e| format!("Unable to write manifest: {e}"))
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Writes `image` to `target_path`, reusing an existing file with the same content
+/// (tracked in the manifest) via a hard link instead of writing the bytes again.
+fn write_deduped(image_dir: &Path, target_path: &Path, image: &[u8]) -> Result<(), String> {
+    let mut manifest = load_manifest(image_dir);
+    let hash = sha256_hex(image);
+
+    let known_path = manifest
+        .known_hashes
+        .get(&hash)
+        .map(|filename| image_dir.join(filename))
+        .filter(|path| path.exists());
+
+    let freshly_written = known_path.is_none();
+
+    match known_path {
+        Some(known_path) => hard_link(&known_path, target_path).map_err(|e| {
+            let e = format!("Unable to hardlink existing image: {e}");
+            error!("{e}");
+            e
+        })?,
+        None => write(target_path, image).map_err(|e| {
+            let e = format!("Unable to write image data: {e}");
+            error!("{e}");
+            e
+        })?,
+    }
+
+    if freshly_written {
+        if let Ok(filename) = target_path.strip_prefix(image_dir) {
+            manifest.known_hashes.insert(hash, filename.to_path_buf());
+            save_manifest(image_dir, &manifest)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Downloads and writes the image for a single `ApodData` entry, skipping (with a
+/// warning) instead of erroring if no still image could be resolved for it.
+fn download_apod_entry(
+    client: &Client,
+    image_dir: PathBuf,
+    apod_data: &ApodData,
+) -> Result<Option<PathBuf>, String> {
+    let image_url = match get_image_url(client, apod_data) {
+        Ok(url) => url,
+        Err(e) => {
+            warn!("Skipping {}: {e}", apod_data.date);
+            return Ok(None);
+        }
+    };
+
+    fetch_and_write_image(client, &image_dir, apod_data, &image_url).map(Some)
+}
+
+/// Downloads the image at `url` and writes it into `image_dir`, unless the file it would be
+/// written to (named after the date and the URL's file name) already exists there, in which
+/// case the download is skipped entirely.
+fn fetch_and_write_image(
+    client: &Client,
+    image_dir: &Path,
+    apod_data: &ApodData,
+    url: &Url,
+) -> Result<PathBuf, String> {
+    let target_path = image_dir.join(image_filename(apod_data, url));
+
+    if target_path.exists() {
+        return Ok(target_path);
+    }
+
+    let image = fetch_hd_image(client, url)?;
+
+    write_deduped(image_dir, &target_path, &image)?;
+
+    info!("Wrote image for {} to {}", apod_data.date, target_path.display());
+
+    Ok(target_path)
+}
+
+/// Most Mastodon instances default to a 500 character status limit.
+const MASTODON_CHAR_LIMIT: usize = 500;
+
+fn build_status_text(apod_data: &ApodData) -> String {
+    let attribution = match &apod_data.copyright {
+        Some(copyright) => format!("\n\n{} | {} | © {copyright}", apod_data.title, apod_data.url),
+        None => format!("\n\n{} | {}", apod_data.title, apod_data.url),
+    };
+
+    let explanation_budget = MASTODON_CHAR_LIMIT.saturating_sub(attribution.chars().count());
+    let explanation: String = apod_data.explanation.chars().take(explanation_budget).collect();
+
+    format!("{explanation}{attribution}")
+}
+
+async fn post_status(
+    instance: &str,
+    token: &str,
+    apod_data: &ApodData,
+    file_path: Option<&Path>,
+) -> Result<(), String> {
+    let client = generator(
+        SNS::Mastodon,
+        instance.to_owned(),
+        Some(token.to_owned()),
+        Some(USER_AGENT.to_owned()),
+    );
+
+    let (status, media_ids) = match file_path {
+        Some(file_path) => {
+            let media = client
+                .upload_media(file_path.to_string_lossy().into_owned(), None)
+                .await
+                .map_err(|e| format!("Unable to upload image: {e}"))?;
+            let media_id = match media.json {
+                megalodon::entities::UploadMedia::Attachment(a) => a.id,
+                megalodon::entities::UploadMedia::AsyncAttachment(a) => a.id,
+            };
+            (build_status_text(apod_data), Some(vec![media_id]))
+        }
+        None => (
+            format!("{}\n\n{}", apod_data.title, apod_data.url),
+            None,
+        ),
+    };
+
+    client
+        .post_status(
+            status,
+            Some(&PostStatusInputOptions {
+                media_ids,
+                ..Default::default()
+            }),
+        )
+        .await
+        .map_err(|e| format!("Unable to post status: {e}"))?;
+
+    Ok(())
+}
+
+/// Posts `apod_data` (with `file_path`'s image attached, if any) to Mastodon, if
+/// `mastodon_instance` and `mastodon_token` are configured. Does nothing otherwise.
+fn post_to_mastodon(
+    runtime: &Runtime,
+    config: &Config,
+    apod_data: &ApodData,
+    file_path: Option<&Path>,
+) -> Result<(), String> {
+    let (Some(instance), Some(token)) = (&config.mastodon_instance, &config.mastodon_token) else {
+        return Ok(());
+    };
+
+    runtime.block_on(post_status(instance, token, apod_data, file_path))
+}
+
+/// Renders the image at `path` inline in the terminal, sized to the terminal width.
+fn preview_image(path: &Path) -> Result<(), String> {
+    let config = ViuerConfig {
+        absolute_offset: false,
+        ..Default::default()
+    };
+
+    viuer::print_from_file(path, &config)
+        .map(|_| ())
+        .map_err(|e| format!("Unable to preview image: {e}"))
+}
+
+fn run() -> Result<(), String> {
+    let cli = Cli::parse();
+
     let client = ClientBuilder::new()
         .user_agent(USER_AGENT)
         .tcp_keepalive(Duration::from_secs(60))
@@ -135,30 +552,67 @@ fn main() -> Result<(), String> {
 
     let api_key = match &config.api_key {
         None => {
-            eprintln!("No api key found in config. Using DEMO_KEY");
+            warn!("No api key found in config. Using DEMO_KEY");
             "DEMO_KEY"
         }
         Some(api_key) => api_key,
     };
 
-    let apod_data = fetch_current_data(&client, api_key)?;
+    let image_dir = config.image_dir.clone().unwrap_or_else(|| PathBuf::from("."));
 
-    let image_url = get_image_url(&apod_data)?;
+    let runtime = Runtime::new().map_err(|e| format!("Unable to start async runtime: {e}"))?;
 
-    let hd_image = fetch_hd_image(&client, image_url)?;
+    if let (Some(start_date), Some(end_date)) = (cli.start_date, cli.end_date) {
+        let apod_data_range = fetch_data_range(&client, api_key, start_date, end_date)?;
+        for apod_data in &apod_data_range {
+            let file_path = download_apod_entry(&client, image_dir.clone(), apod_data)?;
+            if let Err(e) = post_to_mastodon(&runtime, &config, apod_data, file_path.as_deref()) {
+                error!("Unable to post {} to Mastodon: {e}", apod_data.date);
+            }
+            if let Some(file_path) = file_path {
+                if cli.preview {
+                    if let Err(e) = preview_image(&file_path) {
+                        error!("{e}");
+                    }
+                }
+                println!("{}", file_path.to_string_lossy());
+            }
+        }
+        return Ok(());
+    }
 
-    let file_path = write_image(
-        config.image_dir.unwrap_or_else(|| PathBuf::from(".")),
-        &apod_data,
-        image_url,
-        &hd_image,
-    )?;
+    let apod_data = fetch_current_data(&client, api_key, cli.date)?;
 
-    println!("{}", file_path.to_string_lossy());
+    let file_path = download_apod_entry(&client, image_dir, &apod_data)?;
+
+    if let Err(e) = post_to_mastodon(&runtime, &config, &apod_data, file_path.as_deref()) {
+        error!("Unable to post {} to Mastodon: {e}", apod_data.date);
+    }
+
+    if let Some(file_path) = file_path {
+        if cli.preview {
+            if let Err(e) = preview_image(&file_path) {
+                error!("{e}");
+            }
+        }
+        println!("{}", file_path.to_string_lossy());
+    }
 
     Ok(())
 }
 
+fn main() -> ExitCode {
+    env_logger::init();
+
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -237,6 +691,15 @@ mod tests {
         )
     }
 
+    #[test]
+    fn backoff_delay_grows_exponentially_and_is_capped() {
+        // given / when / then
+        assert_eq!(backoff_delay(0), Duration::from_millis(500));
+        assert_eq!(backoff_delay(1), Duration::from_secs(1));
+        assert_eq!(backoff_delay(2), Duration::from_secs(2));
+        assert_eq!(backoff_delay(10), MAX_RETRY_DELAY);
+    }
+
     #[test]
     fn image_filename_handles_spaces_correctly() {
         // given
@@ -257,4 +720,174 @@ mod tests {
         // then
         assert_eq!(&path, "2024-04-19_NGC3372_ETA CARINA_LOPES.jpg");
     }
+
+    #[test]
+    fn youtube_video_id_extracts_from_embed_url() {
+        // given
+        let url = Url::parse("https://www.youtube.com/embed/dQw4w9WgXcQ?rel=0")
+            .expect("expected valid URL");
+
+        // when / then
+        assert_eq!(youtube_video_id(&url), Some("dQw4w9WgXcQ".to_owned()));
+    }
+
+    #[test]
+    fn youtube_video_id_extracts_from_watch_url() {
+        // given
+        let url = Url::parse("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=10")
+            .expect("expected valid URL");
+
+        // when / then
+        assert_eq!(youtube_video_id(&url), Some("dQw4w9WgXcQ".to_owned()));
+    }
+
+    #[test]
+    fn youtube_video_id_extracts_from_short_url() {
+        // given
+        let url = Url::parse("https://youtu.be/dQw4w9WgXcQ").expect("expected valid URL");
+
+        // when / then
+        assert_eq!(youtube_video_id(&url), Some("dQw4w9WgXcQ".to_owned()));
+    }
+
+    #[test]
+    fn youtube_video_id_is_none_for_unrecognized_provider() {
+        // given
+        let url = Url::parse("https://mars.nasa.gov/layout/embed/image/mars-panorama/?id=25674")
+            .expect("expected valid URL");
+
+        // when / then
+        assert_eq!(youtube_video_id(&url), None);
+    }
+
+    fn temp_image_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "apod-test-{name}-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        std::fs::create_dir_all(&dir).expect("expected to create temp dir");
+        dir
+    }
+
+    #[test]
+    fn write_deduped_hardlinks_identical_content_under_a_different_path() {
+        // given
+        let image_dir = temp_image_dir("write_deduped");
+        let first_path = image_dir.join("first.jpg");
+        let second_path = image_dir.join("second.jpg");
+        let image = b"some image bytes";
+
+        // when
+        write_deduped(&image_dir, &first_path, image).expect("expected first write to succeed");
+        write_deduped(&image_dir, &second_path, image).expect("expected second write to succeed");
+
+        // then
+        let manifest = load_manifest(&image_dir);
+        assert_eq!(
+            manifest.known_hashes.get(&sha256_hex(image)),
+            Some(&PathBuf::from("first.jpg"))
+        );
+        assert_eq!(read(&first_path).expect("expected first file to exist"), image);
+        assert_eq!(read(&second_path).expect("expected second file to exist"), image);
+
+        let first_meta = std::fs::metadata(&first_path).expect("expected first metadata");
+        let second_meta = std::fs::metadata(&second_path).expect("expected second metadata");
+        assert_eq!(
+            std::os::unix::fs::MetadataExt::ino(&first_meta),
+            std::os::unix::fs::MetadataExt::ino(&second_meta),
+            "expected second file to be a hard link to the first"
+        );
+
+        std::fs::remove_dir_all(&image_dir).expect("expected to clean up temp dir");
+    }
+
+    #[test]
+    fn build_status_text_keeps_short_explanation_untruncated() {
+        // given
+        let apod_data = ApodData {
+            copyright: None,
+            date: NaiveDate::from_ymd_opt(2021, 3, 8).expect("expected valid date"),
+            explanation: "A short explanation.".to_owned(),
+            title: "Three Tails of Comet NEOWISE".to_owned(),
+            url: Url::parse("https://apod.nasa.gov/apod/image/2103/foo.jpg")
+                .expect("expected valid URL"),
+            media: MediaType::Video {},
+        };
+
+        // when
+        let status = build_status_text(&apod_data);
+
+        // then
+        assert_eq!(
+            status,
+            "A short explanation.\n\nThree Tails of Comet NEOWISE | https://apod.nasa.gov/apod/image/2103/foo.jpg"
+        );
+    }
+
+    #[test]
+    fn build_status_text_truncates_long_explanation_to_fit_char_limit() {
+        // given
+        let apod_data = ApodData {
+            copyright: None,
+            date: NaiveDate::from_ymd_opt(2021, 3, 8).expect("expected valid date"),
+            explanation: "x".repeat(MASTODON_CHAR_LIMIT * 2),
+            title: "Three Tails of Comet NEOWISE".to_owned(),
+            url: Url::parse("https://apod.nasa.gov/apod/image/2103/foo.jpg")
+                .expect("expected valid URL"),
+            media: MediaType::Video {},
+        };
+
+        // when
+        let status = build_status_text(&apod_data);
+
+        // then
+        assert_eq!(status.chars().count(), MASTODON_CHAR_LIMIT);
+        assert!(status.ends_with(
+            "\n\nThree Tails of Comet NEOWISE | https://apod.nasa.gov/apod/image/2103/foo.jpg"
+        ));
+    }
+
+    #[test]
+    fn build_status_text_includes_copyright_when_present() {
+        // given
+        let apod_data = ApodData {
+            copyright: Some("Nicolas Lefaudeux".to_owned()),
+            date: NaiveDate::from_ymd_opt(2021, 3, 8).expect("expected valid date"),
+            explanation: "A short explanation.".to_owned(),
+            title: "Three Tails of Comet NEOWISE".to_owned(),
+            url: Url::parse("https://apod.nasa.gov/apod/image/2103/foo.jpg")
+                .expect("expected valid URL"),
+            media: MediaType::Video {},
+        };
+
+        // when
+        let status = build_status_text(&apod_data);
+
+        // then
+        assert_eq!(
+            status,
+            "A short explanation.\n\nThree Tails of Comet NEOWISE | https://apod.nasa.gov/apod/image/2103/foo.jpg | © Nicolas Lefaudeux"
+        );
+    }
+
+    #[test]
+    fn extract_preview_image_url_finds_first_img_tag() {
+        // given
+        let page_url =
+            Url::parse("https://apod.nasa.gov/apod/ap210309.html").expect("expected valid URL");
+        let html = r#"<html><body><a href="image/2103/foo.jpg"><IMG SRC="image/2103/foo_bar.jpg"></a></body></html>"#;
+
+        // when
+        let image_url = extract_preview_image_url(html, &page_url);
+
+        // then
+        assert_eq!(
+            image_url,
+            Some(
+                Url::parse("https://apod.nasa.gov/apod/image/2103/foo_bar.jpg")
+                    .expect("expected valid URL")
+            )
+        );
+    }
 }